@@ -1,6 +1,6 @@
 #![allow(clippy::map_entry)]
 
-use super::{Pair, WithFirstLastIterator, Word, BPE};
+use super::{Merges, Pair, Vocab, WithFirstLastIterator, Word, BPE};
 use crate::parallelism::*;
 use crate::tokenizer::{AddedToken, Result, Trainer};
 use crate::utils::progress::{ProgressBar, ProgressStyle};
@@ -8,17 +8,46 @@ use regex_syntax::ast::print;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet};
-use std::io::Read;
+
+/// The in-memory state to continue training from, as an alternative to starting a brand
+/// new vocabulary. Mirrors the `vocab`/`merges` pair accepted by `BPE::from_files`, plus the
+/// alphabet that was used to seed the previous run.
+type ContinuationState = (Vocab, Merges, Vec<(String, u32)>);
+
+/// The objective used to rank candidate merges against each other
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeScore {
+    /// Rank merges by raw pair frequency, as in the original BPE algorithm
+    Frequency,
+    /// Rank merges by the corpus likelihood they maximize, WordPiece-style:
+    /// `count(a, b) / (count(a) * count(b))`
+    Likelihood,
+}
+
+impl Default for MergeScore {
+    fn default() -> Self {
+        MergeScore::Frequency
+    }
+}
+
+/// Fixed-point scale applied to `MergeScore::Likelihood` scores so they can be stored as an
+/// orderable `i64` alongside `MergeScore::Frequency`'s raw counts. The score itself is kept
+/// in log space (see `score_pair`) so this only needs to preserve precision across a modest
+/// range, rather than across the many orders of magnitude `count / (count_a * count_b)`
+/// spans on its own.
+const LIKELIHOOD_SCALE: f64 = 1e9;
 
 #[derive(Debug, Eq)]
 struct Merge {
     pair: Pair,
     count: u64,
+    score: i64,
     pos: HashSet<usize>,
 }
 impl PartialEq for Merge {
     fn eq(&self, other: &Self) -> bool {
-        self.count == other.count && self.pair == other.pair
+        self.score == other.score && self.pair == other.pair
     }
 }
 impl PartialOrd for Merge {
@@ -28,8 +57,8 @@ impl PartialOrd for Merge {
 }
 impl Ord for Merge {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.count != other.count {
-            self.count.cmp(&other.count)
+        if self.score != other.score {
+            self.score.cmp(&other.score)
         } else {
             // Here we want ascending order
             other.pair.cmp(&self.pair)
@@ -47,6 +76,12 @@ struct Config {
     continuing_subword_prefix: Option<String>,
     end_of_word_suffix: Option<String>,
     max_token_length: Option<usize>,
+    continue_from: Option<ContinuationState>,
+    superword_transition: Option<usize>,
+    max_words_per_token: usize,
+    merge_score: MergeScore,
+    byte_fallback: bool,
+    split_digits: bool,
 }
 
 /// A `BpeTrainerBuilder` can be used to create a `BpeTrainer` with a custom
@@ -68,6 +103,12 @@ impl Default for BpeTrainerBuilder {
                 continuing_subword_prefix: None,
                 end_of_word_suffix: None,
                 max_token_length: None,
+                continue_from: None,
+                superword_transition: None,
+                max_words_per_token: 20,
+                merge_score: MergeScore::Frequency,
+                byte_fallback: false,
+                split_digits: false,
             },
         }
     }
@@ -141,6 +182,57 @@ impl BpeTrainerBuilder {
         self
     }
 
+    /// Continue training from an existing vocabulary, merges list and alphabet, instead of
+    /// starting from scratch. When set, `do_train` always extends this state rather than
+    /// computing a fresh alphabet from the fed words.
+    #[must_use]
+    pub fn continue_from(mut self, vocab: Vocab, merges: Merges, alphabet: Vec<(String, u32)>) -> Self {
+        self.config.continue_from = Some((vocab, merges, alphabet));
+        self
+    }
+
+    /// Set the vocabulary size at which the trainer stops forbidding merges that cross a
+    /// `Ġ` (word-boundary) marker and starts learning multi-word "superword" tokens, SuperBPE
+    /// style. `None` (the default) never forbids crossing merges, reproducing the original
+    /// trainer's behavior.
+    #[must_use]
+    pub fn superword_transition(mut self, transition: Option<usize>) -> Self {
+        self.config.superword_transition = transition;
+        self
+    }
+
+    /// Set the maximum number of words a single merged token may span once
+    /// `superword_transition` has been reached
+    #[must_use]
+    pub fn max_words_per_token(mut self, max_words_per_token: usize) -> Self {
+        self.config.max_words_per_token = max_words_per_token;
+        self
+    }
+
+    /// Set the objective used to rank candidate merges against each other
+    #[must_use]
+    pub fn merge_score(mut self, merge_score: MergeScore) -> Self {
+        self.config.merge_score = merge_score;
+        self
+    }
+
+    /// Set whether to seed the vocabulary with the 256 `<0xXX>` byte-fallback tokens, so any
+    /// input can be encoded even when a character was never seen in training
+    #[must_use]
+    pub fn byte_fallback(mut self, byte_fallback: bool) -> Self {
+        self.config.byte_fallback = byte_fallback;
+        self
+    }
+
+    /// Set whether to forbid merges that join a digit with another character, forcing every
+    /// digit to remain its own token (SentencePiece-style `split_digits`). Defaults to `false`,
+    /// which allows digit merges as in plain BPE.
+    #[must_use]
+    pub fn split_digits(mut self, split_digits: bool) -> Self {
+        self.config.split_digits = split_digits;
+        self
+    }
+
     /// Constructs the final BpeTrainer
     pub fn build(self) -> BpeTrainer {
         BpeTrainer {
@@ -153,6 +245,12 @@ impl BpeTrainerBuilder {
             continuing_subword_prefix: self.config.continuing_subword_prefix,
             end_of_word_suffix: self.config.end_of_word_suffix,
             max_token_length: self.config.max_token_length,
+            continue_from: self.config.continue_from,
+            superword_transition: self.config.superword_transition,
+            max_words_per_token: self.config.max_words_per_token,
+            merge_score: self.config.merge_score,
+            byte_fallback: self.config.byte_fallback,
+            split_digits: self.config.split_digits,
             words: HashMap::new(),
         }
     }
@@ -196,7 +294,24 @@ pub struct BpeTrainer {
     pub end_of_word_suffix: Option<String>,
     /// An optional parameter to limit the max length of any single token
     pub max_token_length: Option<usize>,
-
+    /// The vocabulary size at which merges crossing a `Ġ` word boundary
+    /// stop being forbidden and multi-word "superword" tokens start being learned. `None`
+    /// never forbids them, matching the original trainer's behavior.
+    pub superword_transition: Option<usize>,
+    /// The maximum number of words a single merged token may span once
+    /// `superword_transition` has been reached
+    pub max_words_per_token: usize,
+    /// The objective used to rank candidate merges against each other
+    pub merge_score: MergeScore,
+    /// Whether to seed the vocabulary with the 256 `<0xXX>` byte-fallback tokens, so any
+    /// input can be encoded even when a character was never seen in training
+    pub byte_fallback: bool,
+    /// Whether to forbid merges that join a digit with another character, forcing every
+    /// digit to remain its own token
+    pub split_digits: bool,
+
+    /// The vocabulary, merges and alphabet to continue training from, if any
+    continue_from: Option<ContinuationState>,
     words: HashMap<String, u64>,
 }
 
@@ -254,27 +369,64 @@ impl BpeTrainer {
 
     /// Add the provided special tokens to the initial vocabulary
     fn add_special_tokens(&self, w2id: &mut HashMap<String, u32>, id2w: &mut Vec<String>) {
-        // Read special tokens from special_tokens.txt file
-        let mut file = std::fs::File::open("special_tokens.txt").unwrap();
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-
-        for line in contents.lines() {
-            // Each line is "token token_id" separated by space
-            let mut split = line.split(' ');
-            let token = split.next().unwrap();
-            let token_id: u32 = split.next().unwrap().parse().unwrap();
-
-            // Check that token_id matches the current length of id_to_word
-            if token_id != id2w.len() as u32 {
-                panic!("Expected token_id to be {}, but got {} for token '{}'", id2w.len(), token_id, token);
+        for token in &self.special_tokens {
+            if !w2id.contains_key(&token.content) {
+                id2w.push(token.content.to_owned());
+                w2id.insert(token.content.to_owned(), (id2w.len() - 1) as u32);
+            }
+        }
+    }
+
+    /// Count how many `Ġ`-delimited words a candidate token spans
+    fn count_words(token: &str) -> usize {
+        token.split('Ġ').filter(|s| !s.is_empty()).count()
+    }
+
+    /// Whether a merge producing `new_token` should be skipped given the current vocabulary
+    /// size, according to `superword_transition`/`max_words_per_token`
+    fn should_skip_superword_merge(&self, current_vocab_size: usize, new_token: &str) -> bool {
+        match self.superword_transition {
+            None => false,
+            Some(transition) => {
+                let num_words = Self::count_words(new_token);
+                if current_vocab_size < transition {
+                    num_words > 1
+                } else {
+                    num_words > self.max_words_per_token
+                }
+            }
+        }
+    }
+
+    /// Add the 256 `<0xXX>` byte-fallback tokens to the vocabulary, right after the special
+    /// tokens, so they get stable ids
+    fn add_byte_fallback_tokens(&self, w2id: &mut HashMap<String, u32>, id2w: &mut Vec<String>) {
+        for byte in 0..=255u8 {
+            let token = format!("<0x{byte:02X}>");
+            if !w2id.contains_key(&token) {
+                id2w.push(token.clone());
+                w2id.insert(token, (id2w.len() - 1) as u32);
             }
+        }
+    }
 
-            if !w2id.contains_key(token) {
-                id2w.push(token.to_owned());
-                w2id.insert(token.to_owned(), token_id);
-            } else {
-                panic!("Token '{}' already exists in vocabulary", token);
+    /// Compute the ordering key for a candidate merge, according to `merge_score`
+    fn score_pair(&self, pair: Pair, count: i64, token_counts: &HashMap<u32, i64>) -> i64 {
+        match self.merge_score {
+            MergeScore::Frequency => count,
+            MergeScore::Likelihood => {
+                // A missing or non-positive symbol count falls back to `1` rather than
+                // `count`, which would otherwise skew the ratio towards the pair itself
+                let count_a = token_counts.get(&pair.0).copied().unwrap_or(1).max(1) as f64;
+                let count_b = token_counts.get(&pair.1).copied().unwrap_or(1).max(1) as f64;
+                // Score in log space: `count / (count_a * count_b)` spans so many orders of
+                // magnitude for a realistic corpus that a linear fixed-point scale either
+                // overflows `i64` or rounds every rare pair down to the same 0, losing the
+                // ranking entirely. `ln` keeps the range bounded while staying monotonic, so
+                // the fixed-point cast below only needs to preserve a modest amount of
+                // precision rather than ~1e-14-scale ratios.
+                let log_score = (count.max(1) as f64).ln() - count_a.ln() - count_b.ln();
+                (log_score * LIKELIHOOD_SCALE) as i64
             }
         }
     }
@@ -352,12 +504,21 @@ impl BpeTrainer {
         for (word, count) in wc {
             let mut current_word = Word::new();
             counts.push(*count);
+            let mut prev_is_digit = false;
 
             for (is_first, is_last, c) in word.chars().with_first_and_last() {
+                let is_digit = c.is_ascii_digit();
                 let mut s = c.to_string();
                 if w2id.contains_key(&s) {
                     // Found the initial char in the authorized alphabet
 
+                    // With `split_digits`, treat a digit run as its own segment: it never
+                    // inherits a `continuing_subword_prefix` from the previous (non-digit)
+                    // char. This only matters when `continuing_subword_prefix` is set; the
+                    // actual "each digit stays its own token" guarantee comes from the
+                    // digit-adjacent merges being forbidden below, regardless of prefix.
+                    let is_first = is_first || (self.split_digits && is_digit != prev_is_digit);
+
                     // Add the `continuing_subword_prefix` if relevant
                     if !is_first {
                         if let Some(prefix) = &self.continuing_subword_prefix {
@@ -379,6 +540,7 @@ impl BpeTrainer {
                     }
                     current_word.add(w2id[&s], 1); // We do not care about the len here
                 }
+                prev_is_digit = is_digit;
             }
             words.push(current_word);
 
@@ -395,13 +557,22 @@ impl BpeTrainer {
         words: &[Word],
         counts: &[u64],
         p: &Option<ProgressBar>,
-    ) -> (HashMap<Pair, i64>, HashMap<Pair, HashSet<usize>>) {
+    ) -> (
+        HashMap<Pair, i64>,
+        HashMap<Pair, HashSet<usize>>,
+        HashMap<u32, i64>,
+    ) {
         words
             .maybe_par_iter()
             .enumerate()
             .map(|(i, word)| {
                 let mut pair_counts = HashMap::new();
                 let mut where_to_update: HashMap<Pair, HashSet<usize>> = HashMap::new();
+                let mut token_counts: HashMap<u32, i64> = HashMap::new();
+
+                for &symbol in word.get_chars() {
+                    *token_counts.entry(symbol).or_insert(0) += counts[i] as i64;
+                }
 
                 for window in word.get_chars().windows(2) {
                     let cur_pair: Pair = (window[0], window[1]);
@@ -430,11 +601,11 @@ impl BpeTrainer {
                     p.inc(1);
                 }
 
-                (pair_counts, where_to_update)
+                (pair_counts, where_to_update, token_counts)
             })
             .reduce(
-                || (HashMap::new(), HashMap::new()),
-                |(mut pair_counts, mut where_to_update), (pc, wtu)| {
+                || (HashMap::new(), HashMap::new(), HashMap::new()),
+                |(mut pair_counts, mut where_to_update, mut token_counts), (pc, wtu, tc)| {
                     for (k, v) in pc {
                         pair_counts.entry(k).and_modify(|c| *c += v).or_insert(v);
                     }
@@ -444,7 +615,10 @@ impl BpeTrainer {
                             .and_modify(|set| *set = set.union(&v).copied().collect())
                             .or_insert(v);
                     }
-                    (pair_counts, where_to_update)
+                    for (k, v) in tc {
+                        token_counts.entry(k).and_modify(|c| *c += v).or_insert(v);
+                    }
+                    (pair_counts, where_to_update, token_counts)
                 },
             )
     }
@@ -454,14 +628,10 @@ impl BpeTrainer {
         word_counts: &HashMap<String, u64>,  // these are counts of whitespace-delimited words
         model: &mut BPE,
     ) -> Result<Vec<AddedToken>> {
-        let file = std::fs::File::open("merges.txt");
-        
-        if file.is_ok() {  // If merges.txt exists, extend it
-            println!("Calling do_train_extend()");
-            return self.do_train_extend(word_counts, model);
-        } else {  // Else, train from scratch
-            println!("Calling do_train_original()");
-            return self.do_train_original(word_counts, model);
+        if self.continue_from.is_some() {
+            self.do_train_extend(word_counts, model)
+        } else {
+            self.do_train_original(word_counts, model)
         }
     }
 
@@ -470,98 +640,62 @@ impl BpeTrainer {
         word_counts: &HashMap<String, u64>,  // these are counts of whitespace-delimited words
         model: &mut BPE,
     ) -> Result<Vec<AddedToken>> {
-        println!("In do_train_extend()");
-
-        // These are mappings between tokens and indices
+        let (vocab, merge_order, alphabet) = self
+            .continue_from
+            .as_ref()
+            .expect("do_train_extend requires continue_from to be set on the BpeTrainer");
+        let merge_order: Merges = merge_order.clone();
+
+        // These are mappings between tokens and indices, seeded from the vocabulary we are
+        // continuing from
         let mut word_to_id: HashMap<String, u32> = HashMap::with_capacity(self.vocab_size);
         let mut id_to_word: Vec<String> = Vec::with_capacity(self.vocab_size);
         let max_token_length: usize = self.max_token_length.unwrap_or(usize::MAX);
 
-        // Read file merges.txt
-        let mut file = std::fs::File::open("merges.txt").unwrap();
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-        let mut lines = contents.lines();
-        let mut merge_order: Vec<(String, String)> = Vec::new();
-        
-        // Remove the first line, which contains the version number
-        lines.next();
-
-        // Loop over the remaining lines
-        for line in lines {
-            // Line is left and right half separated by a space
-            let mut split = line.split(" ");
-            // Add the merge to merge_order
-            merge_order.push((split.next().unwrap().to_string(), split.next().unwrap().to_string()));
-        }
-        
-        // print merge_order
-        // println!("Printing merge_order");
-        // for (left, right) in &merge_order {
-        //     println!("{} + {}", left, right);
-        // }
-
         let progress = self.setup_progress();
 
         //
-        // 1. Load the initial alphabet from alphabet.txt
+        // 1. Load the previous vocabulary, then extend its alphabet with any characters the
+        //    previous training run did not cover
         //
-        println!("Step 1: Load alphabet from file");
-        let mut alphabet_file = std::fs::File::open("alphabet.txt").unwrap();
-        let mut alphabet_contents = String::new();
-        alphabet_file.read_to_string(&mut alphabet_contents).unwrap();
-
-        for line in alphabet_contents.lines() {
-            // Each line is "token token_id" separated by space
-            let mut split = line.split(' ');
-            let token = split.next().unwrap().to_string();
-            let token_id: u32 = split.next().unwrap().parse().unwrap();
-
-            // Ensure token_id matches the current length of id_to_word
-            if id_to_word.len() != token_id as usize {
-                panic!("Expected token_id to be {}, but got {}", id_to_word.len(), token_id);
+        self.update_progress(&progress, vocab.len() + alphabet.len(), "Load vocabulary");
+        for (token, &id) in vocab {
+            if id_to_word.len() <= id as usize {
+                id_to_word.resize(id as usize + 1, String::new());
             }
-
-            // Push the token to the end of the vector
-            id_to_word.push(token.clone());
-            word_to_id.insert(token, token_id);
+            id_to_word[id as usize] = token.clone();
+            word_to_id.insert(token.clone(), id);
         }
-        
-        // Print the length of word_to_id
-        println!("Length of word_to_id: {}", word_to_id.len());
-
-        // Print 10 elements in word_to_id
-        println!("Printing 10 elements in word_to_id");
-        let mut count = 0;
-        for (word, id) in &word_to_id {
-            println!("Word: {}, ID: {}", word, id);
-            count += 1;
-            if count == 10 {
-                break;
+        for (token, _) in alphabet {
+            if !word_to_id.contains_key(token) {
+                id_to_word.push(token.clone());
+                word_to_id.insert(token.clone(), (id_to_word.len() - 1) as u32);
             }
         }
+        self.finalize_progress(&progress, id_to_word.len());
+
+        //
+        // 2. Add all special tokens to the vocabulary (internally modifies word_to_id and id_to_word)
+        //
+        self.add_special_tokens(&mut word_to_id, &mut id_to_word);
+        if self.byte_fallback {
+            self.add_byte_fallback_tokens(&mut word_to_id, &mut id_to_word);
+        }
 
         //
-        // 2. Tokenize words: turn real words into tokens based on the initial alphabet
+        // 3. Tokenize words: turn real words into tokens based on the initial alphabet
         //
-        println!("Step 2: Tokenize words");
         self.update_progress(&progress, word_counts.len(), "Tokenize words");
         let (words, counts) =
             self.tokenize_words(word_counts, &mut word_to_id, &mut id_to_word, &progress);
         self.finalize_progress(&progress, words.len());
-        
-        // print word_counts
-        // println!("Printing word_counts");
-        // for (word, count) in word_counts {
-        //     println!("Word: {}, Count: {}", word, count);
-        // }
 
         //
-        // 3. Count pairs in words
+        // 4. Count pairs in words
         //
-        println!("Step 3: Count pairs in words");
         self.update_progress(&progress, words.len(), "Count pairs");
-        let (mut pair_counts, mut where_to_update) = self.count_pairs(&words, &counts, &progress);
+        let (mut pair_counts, mut where_to_update, mut token_counts) =
+            self.count_pairs(&words, &counts, &progress);
         // Insert them in the queue
         let mut queue: HashMap<Pair, Merge> = HashMap::new();
         where_to_update.drain().for_each(|(pair, pos)| {
@@ -570,6 +704,7 @@ impl BpeTrainer {
                 let merge = Merge {
                     pair,
                     count: count as u64,
+                    score: self.score_pair(pair, count, &token_counts),
                     pos
                 };
                 // add the merge to the queue
@@ -577,25 +712,14 @@ impl BpeTrainer {
             }
         });
         self.finalize_progress(&progress, words.len());
-        println!("Length of queue: {}", queue.len());
-
-        // println!("Printing queue");
-        // for (pair, merge) in &queue {
-        //     println!("Pair: ({}, {}), Count: {}, Pos: {:?}", id_to_word[pair.0 as usize], id_to_word[pair.1 as usize], merge.count, merge.pos);
-        // }
 
         //
-        // 4. Inherit all the existing merges in merge_order
+        // 5. Inherit all the existing merges in merge_order
         //
-        println!("Step 4: Apply merges");
         self.update_progress(&progress, merge_order.len(), "Compute existing merges");
         let mut merges: Vec<(Pair, u32)> = vec![];
 
         for (left, right) in &merge_order {
-            // print the merge we are applying
-            // println!("-------");
-            // println!("Applying merge: {} + {}", left, right);
-            
             // If tokens from merge are not found in the given data
             if !word_to_id.contains_key(left) || !word_to_id.contains_key(right) {
                 if !word_to_id.contains_key(left) {
@@ -612,8 +736,6 @@ impl BpeTrainer {
             let override_pair = (*left_id, *right_id);
 
             if !queue.contains_key(&override_pair) {
-                println!("{} + {} not found in queue", left, right);
-                
                 // Still inherit the merging rule to vocabulary even if not in queue
                 let part_a = &id_to_word[override_pair.0 as usize];
                 let mut part_b = id_to_word[override_pair.1 as usize].to_owned();
@@ -670,6 +792,11 @@ impl BpeTrainer {
             }
             merges.push((top.pair, new_token_id));
 
+            // Update the individual symbol occurrence counts alongside the pair counts
+            *token_counts.entry(top.pair.0).or_insert(0) -= top.count as i64;
+            *token_counts.entry(top.pair.1).or_insert(0) -= top.count as i64;
+            *token_counts.entry(new_token_id).or_insert(0) += top.count as i64;
+
             // Merge the new pair in every word
             let changes = top
                 .pos
@@ -715,6 +842,7 @@ impl BpeTrainer {
                     queue.insert(pair, Merge {
                         pair,
                         count: count as u64,
+                        score: self.score_pair(pair, count, &token_counts),
                         pos,
                     });
                 }
@@ -726,19 +854,9 @@ impl BpeTrainer {
         }
         self.finalize_progress(&progress, merges.len());
 
-        // print length of merges
-        println!("Length of merges: {}", merges.len());
-
-        //
-        // 5. Add all special tokens to the vocabulary (internally modifies word_to_id and id_to_word)
-        //
-        println!("Step 5: Add special tokens");
-        self.add_special_tokens(&mut word_to_id, &mut id_to_word);
-
         //
         // 6. Add new merges
         //
-        println!("Step 6: Do new merges");
         self.update_progress(&progress, self.vocab_size, "Compute new merges");
         // currently queue is HashMap<Pair, Merge>
         // we want to transform it to BinaryHeap while keeping the same entries
@@ -755,10 +873,12 @@ impl BpeTrainer {
             }
 
             let mut top: Merge = queue.pop().unwrap();
-            
-            if top.count != pair_counts[&top.pair] as u64 {
-                // println!("{} != {} for pair: ({}, {})", top.count, pair_counts[&top.pair], id_to_word[top.pair.0 as usize], id_to_word[top.pair.1 as usize]);
-                top.count = pair_counts[&top.pair] as u64;
+
+            let current_count = pair_counts[&top.pair] as u64;
+            let current_score = self.score_pair(top.pair, current_count as i64, &token_counts);
+            if top.count != current_count || top.score != current_score {
+                top.count = current_count;
+                top.score = current_score;
                 queue.push(top);
                 continue;
             }
@@ -766,8 +886,6 @@ impl BpeTrainer {
             if top.count < 1 || self.min_frequency > top.count {
                 break;
             }
-            
-            // println!("Merging pair: ({}, {}) with count {}", id_to_word[top.pair.0 as usize], id_to_word[top.pair.1 as usize], top.count);
 
             let part_a = &id_to_word[top.pair.0 as usize];
             let mut part_b = id_to_word[top.pair.1 as usize].to_owned();
@@ -781,27 +899,17 @@ impl BpeTrainer {
             }
             let new_token = format!("{}{}", part_a, part_b);
 
-            // special case : by not allowing any tokens that contain :Ġ
-            // if new_token.contains(":Ġ") {
-                // println!("Skipping merge {} {} because of : special-casing", part_a, part_b);
-                // continue;
-            // }
-            
-            // skip any multi-word tokens consisting of n or more Ġ which are not consecutive
-            let num_words = new_token.split("Ġ").filter(|s| !s.is_empty()).count();
-            if num_words > 20 {
-                println!("Skipping merge {} {} because it has {} words", part_a, part_b, num_words);
+            // Forbid merges that cross a `Ġ` word boundary until `superword_transition` is
+            // reached, then allow up to `max_words_per_token` words per token
+            if self.should_skip_superword_merge(word_to_id.len(), &new_token) {
                 continue;
             }
 
-            // println!("New token: {}", new_token);
-            // implement sentencepiece-like merge.
-            // if this code were to be merged, integrate a way in the python bindings to communicate this variable
-            // default should be 0/None to maintain previous behavior. 16 is the spm default.
-
-            // Skip merge if part_a ends with digit or part_b starts with digit
-            if part_a.chars().last().map_or(false, |c| c.is_ascii_digit()) || part_b.chars().next().map_or(false, |c| c.is_ascii_digit()) {
-                println!("Skipping merge {} {} because part_a ends with digit or part_b starts with digit", part_a, part_b);
+            // With `split_digits`, forbid merges joining a digit with another character
+            if self.split_digits
+                && (part_a.chars().last().map_or(false, |c| c.is_ascii_digit())
+                    || part_b.chars().next().map_or(false, |c| c.is_ascii_digit()))
+            {
                 continue;
             }
 
@@ -816,6 +924,11 @@ impl BpeTrainer {
             }
             merges.push((top.pair, new_token_id));
 
+            // Update the individual symbol occurrence counts alongside the pair counts
+            *token_counts.entry(top.pair.0).or_insert(0) -= top.count as i64;
+            *token_counts.entry(top.pair.1).or_insert(0) -= top.count as i64;
+            *token_counts.entry(new_token_id).or_insert(0) += top.count as i64;
+
             // Merge the new pair in every word
             let changes = top
                 .pos
@@ -861,6 +974,7 @@ impl BpeTrainer {
                     queue.push(Merge {
                         pair,
                         count: count as u64,
+                        score: self.score_pair(pair, count, &token_counts),
                         pos,
                     });
                 }
@@ -895,6 +1009,7 @@ impl BpeTrainer {
         } else {
             model.end_of_word_suffix = None;
         }
+        model.byte_fallback = self.byte_fallback;
 
         Ok(self.special_tokens.clone())
     }
@@ -914,6 +1029,9 @@ impl BpeTrainer {
         // 1. Add all special tokens to the vocabulary
         //
         self.add_special_tokens(&mut word_to_id, &mut id_to_word);
+        if self.byte_fallback {
+            self.add_byte_fallback_tokens(&mut word_to_id, &mut id_to_word);
+        }
 
         //
         // 2. Compute the initial alphabet
@@ -932,7 +1050,8 @@ impl BpeTrainer {
         // 4. Count pairs in words
         //
         self.update_progress(&progress, words.len(), "Count pairs");
-        let (mut pair_counts, mut where_to_update) = self.count_pairs(&words, &counts, &progress);
+        let (mut pair_counts, mut where_to_update, mut token_counts) =
+            self.count_pairs(&words, &counts, &progress);
         // Insert them in the queue
         let mut queue = BinaryHeap::with_capacity(pair_counts.len());
         where_to_update.drain().for_each(|(pair, pos)| {
@@ -941,6 +1060,7 @@ impl BpeTrainer {
                 queue.push(Merge {
                     pair,
                     count: count as u64,
+                    score: self.score_pair(pair, count, &token_counts),
                     pos,
                 });
             }
@@ -963,8 +1083,11 @@ impl BpeTrainer {
             }
 
             let mut top = queue.pop().unwrap();
-            if top.count != pair_counts[&top.pair] as u64 {
-                top.count = pair_counts[&top.pair] as u64;
+            let current_count = pair_counts[&top.pair] as u64;
+            let current_score = self.score_pair(top.pair, current_count as i64, &token_counts);
+            if top.count != current_count || top.score != current_score {
+                top.count = current_count;
+                top.score = current_score;
                 queue.push(top);
                 continue;
             }
@@ -976,10 +1099,6 @@ impl BpeTrainer {
             let part_a = &id_to_word[top.pair.0 as usize];
             let mut part_b = id_to_word[top.pair.1 as usize].to_owned();
 
-            // if (part_a.contains("Ġ") || part_b.contains("Ġ")) && !part_a.starts_with("Ġ") {
-            //     continue;
-            // }
-            
             // Build new token
             if let Some(prefix) = &self.continuing_subword_prefix {
                 if part_b.starts_with(prefix) {
@@ -992,6 +1111,20 @@ impl BpeTrainer {
             // if this code were to be merged, integrate a way in the python bindings to communicate this variable
             // default should be 0/None to maintain previous behavior. 16 is the spm default.
 
+            // Forbid merges that cross a `Ġ` word boundary until `superword_transition` is
+            // reached, then allow up to `max_words_per_token` words per token
+            if self.should_skip_superword_merge(word_to_id.len(), &new_token) {
+                continue;
+            }
+
+            // With `split_digits`, forbid merges joining a digit with another character
+            if self.split_digits
+                && (part_a.chars().last().map_or(false, |c| c.is_ascii_digit())
+                    || part_b.chars().next().map_or(false, |c| c.is_ascii_digit()))
+            {
+                continue;
+            }
+
             // Insert new token if it does not already exist
             let new_token_id = word_to_id
                 .get(&new_token)
@@ -1003,6 +1136,11 @@ impl BpeTrainer {
             }
             merges.push((top.pair, new_token_id));
 
+            // Update the individual symbol occurrence counts alongside the pair counts
+            *token_counts.entry(top.pair.0).or_insert(0) -= top.count as i64;
+            *token_counts.entry(top.pair.1).or_insert(0) -= top.count as i64;
+            *token_counts.entry(new_token_id).or_insert(0) += top.count as i64;
+
             // Merge the new pair in every words
             let changes = top
                 .pos
@@ -1048,6 +1186,7 @@ impl BpeTrainer {
                     queue.push(Merge {
                         pair,
                         count: count as u64,
+                        score: self.score_pair(pair, count, &token_counts),
                         pos,
                     });
                 }
@@ -1082,6 +1221,7 @@ impl BpeTrainer {
         } else {
             model.end_of_word_suffix = None;
         }
+        model.byte_fallback = self.byte_fallback;
 
         Ok(self.special_tokens.clone())
     }
@@ -1321,4 +1461,135 @@ mod tests {
         .collect();
         assert_eq!(trained_vocab, expected_vocab)
     }
+
+    #[test]
+    fn test_continue_from_round_trips_a_converged_vocab() {
+        let word_counts: HashMap<String, u64> = [
+            ("roses".into(), 1),
+            ("are".into(), 2),
+            ("red".into(), 1),
+            ("voilets".into(), 1),
+            ("blue".into(), 1),
+            ("BERT".into(), 1),
+            ("is".into(), 2),
+            ("big".into(), 1),
+            ("and".into(), 1),
+            ("so".into(), 1),
+            ("GPT-2".into(), 1),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let trainer = BpeTrainer::builder()
+            .show_progress(false)
+            .min_frequency(2)
+            .build();
+        let mut model = BPE::default();
+        trainer.do_train(&word_counts, &mut model).unwrap();
+
+        // Re-derive the (token, token) `Merges` format `continue_from` expects from the
+        // model's (Pair, (rank, id)) merges, in rank order.
+        let id_to_token: HashMap<u32, String> = model
+            .vocab
+            .iter()
+            .map(|(token, &id)| (id, token.clone()))
+            .collect();
+        let mut merges_by_rank: Vec<(u32, (String, String))> = model
+            .merges
+            .iter()
+            .map(|(pair, &(rank, _new_id))| {
+                (
+                    rank,
+                    (id_to_token[&pair.0].clone(), id_to_token[&pair.1].clone()),
+                )
+            })
+            .collect();
+        merges_by_rank.sort_by_key(|(rank, _)| *rank);
+        let merges: Vec<(String, String)> =
+            merges_by_rank.into_iter().map(|(_, pair)| pair).collect();
+
+        let continued_trainer = BpeTrainer::builder()
+            .show_progress(false)
+            .min_frequency(2)
+            .continue_from(model.vocab.clone(), merges, vec![])
+            .build();
+        let mut continued_model = BPE::default();
+        continued_trainer
+            .do_train(&word_counts, &mut continued_model)
+            .unwrap();
+
+        // Continuing from an already-converged vocabulary on the same corpus should
+        // reproduce it exactly: every inherited merge replays and no new merge qualifies.
+        assert_eq!(continued_model.vocab, model.vocab);
+        assert_eq!(continued_model.merges, model.merges);
+    }
+
+    #[test]
+    fn test_should_skip_superword_merge() {
+        // `None` (the default) never forbids a merge, reproducing the original trainer
+        let default_trainer = BpeTrainer::default();
+        assert!(!default_trainer.should_skip_superword_merge(0, "helloĠworld"));
+
+        let trainer = BpeTrainer::builder()
+            .superword_transition(Some(100))
+            .max_words_per_token(2)
+            .build();
+
+        // Below the transition: any merge spanning more than one word is skipped
+        assert!(trainer.should_skip_superword_merge(50, "helloĠworld"));
+        assert!(!trainer.should_skip_superword_merge(50, "hello"));
+
+        // At/after the transition: multi-word merges are allowed up to max_words_per_token
+        assert!(!trainer.should_skip_superword_merge(100, "helloĠworld"));
+        assert!(trainer.should_skip_superword_merge(100, "helloĠworldĠfoo"));
+    }
+
+    #[test]
+    fn test_split_digits_keeps_every_digit_its_own_token() {
+        // Every adjacent pair in "2024" is digit-digit, so with `split_digits` no merge
+        // should ever qualify: the word stays "2 0 2 4".
+        let word_counts: HashMap<String, u64> = [("2024".to_string(), 10)].iter().cloned().collect();
+        let trainer = BpeTrainer::builder()
+            .show_progress(false)
+            .min_frequency(1)
+            .split_digits(true)
+            .build();
+        let mut model = BPE::default();
+        trainer.do_train(&word_counts, &mut model).unwrap();
+
+        assert!(
+            model.merges.is_empty(),
+            "split_digits should forbid every digit-adjacent merge, found: {:?}",
+            model.merges
+        );
+        let expected_vocab: HashMap<String, u32> = [("0".into(), 0), ("2".into(), 1), ("4".into(), 2)]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(model.vocab, expected_vocab);
+    }
+
+    #[test]
+    fn test_byte_fallback_seeds_256_stable_tokens_after_specials() {
+        let word_counts: HashMap<String, u64> = [("hi".to_string(), 1)].iter().cloned().collect();
+        let trainer = BpeTrainer::builder()
+            .show_progress(false)
+            .byte_fallback(true)
+            .build();
+        let mut model = BPE::default();
+        trainer.do_train(&word_counts, &mut model).unwrap();
+
+        assert!(model.byte_fallback);
+        // No special tokens configured here, so the 256 byte-fallback tokens get the very
+        // first, contiguous ids.
+        for byte in 0..=255u8 {
+            let token = format!("<0x{byte:02X}>");
+            assert_eq!(
+                model.vocab.get(&token),
+                Some(&(byte as u32)),
+                "missing or unstable byte-fallback token {}",
+                token
+            );
+        }
+    }
 }
@@ -0,0 +1,738 @@
+use super::Unigram;
+use crate::parallelism::*;
+use crate::tokenizer::{AddedToken, Result, Trainer};
+use crate::utils::progress::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// One partial (or complete) segmentation explored during the best-first beam search: the
+/// position reached in the word, the pieces chosen so far, and their accumulated log-prob
+#[derive(Debug, Clone)]
+struct SegmentationState {
+    position: usize,
+    pieces: Vec<String>,
+    log_prob: f64,
+}
+impl PartialEq for SegmentationState {
+    fn eq(&self, other: &Self) -> bool {
+        self.log_prob == other.log_prob
+    }
+}
+impl Eq for SegmentationState {}
+impl PartialOrd for SegmentationState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for SegmentationState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // A `BinaryHeap` is a max-heap, so the highest-scoring partial path pops first
+        self.log_prob.total_cmp(&other.log_prob)
+    }
+}
+
+struct Config {
+    vocab_size: usize,
+    show_progress: bool,
+    special_tokens: Vec<AddedToken>,
+    max_piece_length: usize,
+    min_frequency: u64,
+    shrinking_factor: f64,
+    n_em_iterations: usize,
+    beam_size: usize,
+    unk_token: Option<String>,
+}
+
+/// A `UnigramTrainerBuilder` can be used to create a `UnigramTrainer` with a custom
+/// configuration.
+pub struct UnigramTrainerBuilder {
+    config: Config,
+}
+
+impl Default for UnigramTrainerBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config {
+                vocab_size: 8000,
+                show_progress: true,
+                special_tokens: vec![],
+                max_piece_length: 16,
+                min_frequency: 2,
+                shrinking_factor: 0.75,
+                n_em_iterations: 2,
+                beam_size: 5,
+                unk_token: None,
+            },
+        }
+    }
+}
+
+impl UnigramTrainerBuilder {
+    /// Constructs a new `UnigramTrainerBuilder`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the target vocabulary size
+    #[must_use]
+    pub fn vocab_size(mut self, vocab_size: usize) -> Self {
+        self.config.vocab_size = vocab_size;
+        self
+    }
+
+    /// Set whether to show progress
+    #[must_use]
+    pub fn show_progress(mut self, show_progress: bool) -> Self {
+        self.config.show_progress = show_progress;
+        self
+    }
+
+    /// Set the special tokens
+    #[must_use]
+    pub fn special_tokens(mut self, special_tokens: Vec<AddedToken>) -> Self {
+        self.config.special_tokens = special_tokens;
+        self
+    }
+
+    /// Set the maximum length, in chars, of a seeded candidate piece
+    #[must_use]
+    pub fn max_piece_length(mut self, max_piece_length: usize) -> Self {
+        self.config.max_piece_length = max_piece_length;
+        self
+    }
+
+    /// Set the minimum corpus frequency a substring must have to seed a candidate piece
+    #[must_use]
+    pub fn min_frequency(mut self, min_frequency: u64) -> Self {
+        self.config.min_frequency = min_frequency;
+        self
+    }
+
+    /// Set the fraction of prunable pieces *kept* at each prune (SentencePiece
+    /// convention: e.g. 0.75 keeps 75% and drops 25%), never below `vocab_size`
+    #[must_use]
+    pub fn shrinking_factor(mut self, shrinking_factor: f64) -> Self {
+        self.config.shrinking_factor = shrinking_factor;
+        self
+    }
+
+    /// Set the number of EM rounds run between each prune
+    #[must_use]
+    pub fn n_em_iterations(mut self, n_em_iterations: usize) -> Self {
+        self.config.n_em_iterations = n_em_iterations;
+        self
+    }
+
+    /// Set the beam width used by the best-first segmentation search
+    #[must_use]
+    pub fn beam_size(mut self, beam_size: usize) -> Self {
+        self.config.beam_size = beam_size;
+        self
+    }
+
+    /// Set an unknown token, added to the vocabulary if provided
+    #[must_use]
+    pub fn unk_token(mut self, unk_token: Option<String>) -> Self {
+        self.config.unk_token = unk_token;
+        self
+    }
+
+    /// Constructs the final UnigramTrainer
+    pub fn build(self) -> UnigramTrainer {
+        UnigramTrainer {
+            vocab_size: self.config.vocab_size,
+            show_progress: self.config.show_progress,
+            special_tokens: self.config.special_tokens,
+            max_piece_length: self.config.max_piece_length,
+            min_frequency: self.config.min_frequency,
+            shrinking_factor: self.config.shrinking_factor,
+            n_em_iterations: self.config.n_em_iterations,
+            beam_size: self.config.beam_size,
+            unk_token: self.config.unk_token,
+            words: HashMap::new(),
+        }
+    }
+}
+
+/// In charge of training a `Unigram` model: a ULM/SentencePiece-style subword vocabulary
+/// where every piece carries a log-probability, learned with EM rather than an ordered
+/// merge list.
+///
+/// # Examples
+///
+/// ```
+/// use tokenizers::tokenizer::Trainer;
+/// use tokenizers::models::unigram::{Unigram, UnigramTrainer};
+///
+/// let sequences = vec![ "Hello", "World" ];
+///
+/// let mut trainer = UnigramTrainer::default();
+/// trainer.feed(sequences.iter(), |s| Ok(vec![s.to_owned()]));
+///
+/// let mut model = Unigram::default();
+/// let special_tokens = trainer.train(&mut model).unwrap();
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnigramTrainer {
+    /// The target vocabulary size
+    pub vocab_size: usize,
+    /// Whether to show progress while training
+    pub show_progress: bool,
+    /// A list of special tokens that the model should know of
+    pub special_tokens: Vec<AddedToken>,
+    /// The maximum length, in chars, of a seeded candidate piece
+    pub max_piece_length: usize,
+    /// The minimum corpus frequency a substring must have to seed a candidate piece
+    pub min_frequency: u64,
+    /// The fraction of prunable pieces *kept* at each prune (SentencePiece
+    /// convention: e.g. 0.75 keeps 75% and drops 25%), never below `vocab_size`
+    pub shrinking_factor: f64,
+    /// The number of EM rounds run between each prune
+    pub n_em_iterations: usize,
+    /// The beam width used by the best-first segmentation search
+    pub beam_size: usize,
+    /// An optional unknown token, added to the vocabulary if provided
+    pub unk_token: Option<String>,
+
+    words: HashMap<String, u64>,
+}
+
+impl Default for UnigramTrainer {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl UnigramTrainer {
+    pub fn builder() -> UnigramTrainerBuilder {
+        UnigramTrainerBuilder::new()
+    }
+
+    /// Setup a progress bar if asked to show progress
+    fn setup_progress(&self) -> Option<ProgressBar> {
+        if self.show_progress {
+            let p = ProgressBar::new(0);
+            p.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {msg:<30!} {wide_bar} {pos:<9!}/{len:>9!}")
+                    .expect("Invalid progress template"),
+            );
+            Some(p)
+        } else {
+            None
+        }
+    }
+
+    /// Set the progress bar in the finish state
+    fn finalize_progress(&self, p: &Option<ProgressBar>, final_len: usize) {
+        if let Some(p) = p {
+            p.set_length(final_len as u64);
+            p.finish();
+            println!();
+        }
+    }
+
+    /// Update the progress bar with the new provided length and message
+    fn update_progress(&self, p: &Option<ProgressBar>, len: usize, message: &'static str) {
+        if let Some(p) = p {
+            p.set_message(message);
+            p.set_length(len as u64);
+            p.reset();
+        }
+    }
+
+    /// Seed the initial candidate set: every substring up to `max_piece_length` whose
+    /// weighted corpus frequency is at least `min_frequency`, plus every single character (which
+    /// is always kept, so any word can still be fully segmented). Each candidate's initial
+    /// probability is proportional to its frequency.
+    fn seed_pieces(&self, word_counts: &HashMap<String, u64>) -> HashMap<String, f64> {
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for (word, count) in word_counts {
+            let chars: Vec<char> = word.chars().collect();
+            for start in 0..chars.len() {
+                let max_len = self.max_piece_length.min(chars.len() - start);
+                for len in 1..=max_len {
+                    let piece: String = chars[start..start + len].iter().collect();
+                    *counts.entry(piece).or_insert(0) += count;
+                }
+            }
+        }
+
+        let mut pieces: HashMap<String, f64> = counts
+            .into_iter()
+            .filter(|(piece, count)| piece.chars().count() == 1 || *count >= self.min_frequency)
+            .map(|(piece, count)| (piece, count as f64))
+            .collect();
+
+        for word in word_counts.keys() {
+            for c in word.chars() {
+                pieces.entry(c.to_string()).or_insert(1.0);
+            }
+        }
+
+        Self::renormalize(&mut pieces);
+        pieces
+    }
+
+    fn renormalize(pieces: &mut HashMap<String, f64>) {
+        let total: f64 = pieces.values().sum();
+        for prob in pieces.values_mut() {
+            *prob /= total;
+        }
+    }
+
+    /// Best-first beam search over segmentations of `word`, ordered by accumulated
+    /// log-probability: a `BinaryHeap` of partial paths, always expanding the highest-scoring
+    /// one next. Returns up to `beam_size` complete segmentations, best first.
+    fn beam_segment(&self, word: &[char], pieces: &HashMap<String, f64>) -> Vec<(Vec<String>, f64)> {
+        let mut heap: BinaryHeap<SegmentationState> = BinaryHeap::new();
+        heap.push(SegmentationState {
+            position: 0,
+            pieces: vec![],
+            log_prob: 0.0,
+        });
+
+        // Bound the search so a pathological word can't expand forever
+        let max_expansions = (word.len() + 1) * self.beam_size * 4 + 16;
+        let mut expansions = 0;
+        let mut completed: Vec<(Vec<String>, f64)> = vec![];
+
+        while let Some(state) = heap.pop() {
+            if state.position == word.len() {
+                completed.push((state.pieces, state.log_prob));
+                if completed.len() >= self.beam_size {
+                    break;
+                }
+                continue;
+            }
+
+            expansions += 1;
+            if expansions > max_expansions {
+                break;
+            }
+
+            let max_len = self.max_piece_length.min(word.len() - state.position);
+            for len in 1..=max_len {
+                let candidate: String = word[state.position..state.position + len].iter().collect();
+                if let Some(&prob) = pieces.get(&candidate) {
+                    let mut pieces_so_far = state.pieces.clone();
+                    pieces_so_far.push(candidate);
+                    heap.push(SegmentationState {
+                        position: state.position + len,
+                        pieces: pieces_so_far,
+                        log_prob: state.log_prob + prob.ln(),
+                    });
+                }
+            }
+        }
+
+        completed
+    }
+
+    /// E-step: for every word, beam-search its segmentation lattice and distribute its
+    /// (weighted) count across the beam's segmentations, proportionally to how much of the
+    /// beam's total probability mass each one holds. This approximates the true
+    /// forward-backward expected counts while reusing the same best-first search as pruning.
+    fn e_step(
+        &self,
+        word_counts: &HashMap<String, u64>,
+        pieces: &HashMap<String, f64>,
+    ) -> HashMap<String, f64> {
+        let per_word: Vec<HashMap<String, f64>> = word_counts
+            .maybe_par_iter()
+            .map(|(word, &count)| {
+                let chars: Vec<char> = word.chars().collect();
+                let candidates = self.beam_segment(&chars, pieces);
+                let mut local_counts: HashMap<String, f64> = HashMap::new();
+                if candidates.is_empty() {
+                    return local_counts;
+                }
+
+                let max_log_prob = candidates
+                    .iter()
+                    .map(|(_, lp)| *lp)
+                    .fold(f64::NEG_INFINITY, f64::max);
+                let weights: Vec<f64> = candidates
+                    .iter()
+                    .map(|(_, lp)| (*lp - max_log_prob).exp())
+                    .collect();
+                let weight_sum: f64 = weights.iter().sum();
+
+                for ((segmentation, _), weight) in candidates.iter().zip(weights.iter()) {
+                    let share = count as f64 * (weight / weight_sum);
+                    for piece in segmentation {
+                        *local_counts.entry(piece.clone()).or_insert(0.0) += share;
+                    }
+                }
+
+                local_counts
+            })
+            .collect();
+
+        let mut expected_counts: HashMap<String, f64> = HashMap::new();
+        for counts in per_word {
+            for (piece, count) in counts {
+                *expected_counts.entry(piece).or_insert(0.0) += count;
+            }
+        }
+        expected_counts
+    }
+
+    /// M-step: renormalize the expected counts into new piece probabilities, dropping any
+    /// multi-char piece that received no support from the corpus. Every character in
+    /// `all_chars` is always kept (at a small floor count if its expected count is 0 or it
+    /// never won a beam), since dropping one here would silently undo `seed_pieces`'
+    /// full-coverage guarantee as soon as it stops being the E-step's choice for every
+    /// occurrence of that character.
+    fn m_step(
+        &self,
+        expected_counts: HashMap<String, f64>,
+        all_chars: &HashSet<String>,
+    ) -> HashMap<String, f64> {
+        const MIN_CHAR_COUNT: f64 = 1e-9;
+        let mut pieces: HashMap<String, f64> = expected_counts
+            .into_iter()
+            .filter(|(piece, count)| *count > 0.0 || all_chars.contains(piece))
+            .collect();
+        for c in all_chars {
+            let count = pieces.entry(c.clone()).or_insert(MIN_CHAR_COUNT);
+            if *count <= 0.0 {
+                *count = MIN_CHAR_COUNT;
+            }
+        }
+        Self::renormalize(&mut pieces);
+        pieces
+    }
+
+    /// Estimate the loss in corpus log-likelihood that would result from dropping each
+    /// multi-char candidate piece, by re-running the beam search for every word whose best
+    /// segmentation currently uses it, with that piece made unavailable. Single-char pieces
+    /// are never considered, so they can never be pruned.
+    fn compute_losses(
+        &self,
+        word_counts: &HashMap<String, u64>,
+        pieces: &HashMap<String, f64>,
+    ) -> HashMap<String, f64> {
+        let mut users: HashMap<String, Vec<(String, u64, f64)>> = HashMap::new();
+        for (word, &count) in word_counts {
+            let chars: Vec<char> = word.chars().collect();
+            if let Some((segmentation, log_prob)) =
+                self.beam_segment(&chars, pieces).into_iter().next()
+            {
+                let unique_pieces: HashSet<&String> = segmentation.iter().collect();
+                for piece in unique_pieces {
+                    users
+                        .entry(piece.clone())
+                        .or_default()
+                        .push((word.clone(), count, log_prob));
+                }
+            }
+        }
+
+        // Reused across candidates instead of cloning `pieces` for each one: a candidate piece
+        // is removed just before scoring it and restored right after, so at any point this
+        // holds `pieces` minus (at most) the one piece currently being scored.
+        let mut scratch = pieces.clone();
+        let mut losses = HashMap::new();
+        for piece in pieces.keys().filter(|piece| piece.chars().count() > 1) {
+            let prob = scratch.remove(piece).expect("piece came from `pieces`");
+
+            let loss = users
+                .get(piece)
+                .map(|affected| {
+                    affected
+                        .iter()
+                        .map(|(word, count, original_log_prob)| {
+                            let chars: Vec<char> = word.chars().collect();
+                            let new_log_prob = self
+                                .beam_segment(&chars, &scratch)
+                                .into_iter()
+                                .next()
+                                .map(|(_, lp)| lp)
+                                // The word can no longer be segmented without this
+                                // piece: treat it as a very large (but finite) loss
+                                .unwrap_or(f64::MIN / 2.0);
+                            *count as f64 * (original_log_prob - new_log_prob)
+                        })
+                        .sum()
+                })
+                .unwrap_or(0.0);
+
+            scratch.insert(piece.clone(), prob);
+            losses.insert(piece.clone(), loss);
+        }
+        losses
+    }
+
+    /// Drop the lowest-loss fraction of prunable (multi-char) pieces. `shrinking_factor` is
+    /// the fraction of *prunable* pieces to keep each round (SentencePiece convention, so a
+    /// higher value is more conservative); the actual number dropped is further clamped so a
+    /// round never takes the vocabulary below `vocab_size` (single chars in `all_chars`
+    /// always survive regardless, since they're never candidates here).
+    fn prune(
+        &self,
+        pieces: &mut HashMap<String, f64>,
+        word_counts: &HashMap<String, u64>,
+        all_chars: &HashSet<String>,
+    ) {
+        let losses = self.compute_losses(word_counts, pieces);
+        let mut by_loss: Vec<(String, f64)> = losses.into_iter().collect();
+        by_loss.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let keep = ((by_loss.len() as f64) * self.shrinking_factor).ceil() as usize;
+        let wanted_drop = by_loss.len().saturating_sub(keep);
+        let floor = self.vocab_size.max(all_chars.len());
+        let max_droppable = pieces.len().saturating_sub(floor);
+        let to_drop = wanted_drop.min(max_droppable);
+
+        for (piece, _) in by_loss.into_iter().take(to_drop) {
+            pieces.remove(&piece);
+        }
+
+        Self::renormalize(pieces);
+    }
+
+    /// Convert the final piece probabilities into a sorted (piece, log-prob) vocab, truncated
+    /// to `vocab_size`. Every char in `all_chars` is exempt from truncation so the full-coverage
+    /// guarantee holds even when `vocab_size` is smaller than the number of distinct corpus
+    /// characters; only multi-char pieces are cut to fill the remaining budget.
+    fn finalize_vocab(
+        &self,
+        pieces: HashMap<String, f64>,
+        all_chars: &HashSet<String>,
+    ) -> Vec<(String, f64)> {
+        let mut vocab: Vec<(String, f64)> = pieces
+            .into_iter()
+            .map(|(piece, prob)| (piece, prob.ln()))
+            .collect();
+        vocab.sort_by(|a, b| b.1.total_cmp(&a.1));
+        if vocab.len() > self.vocab_size {
+            let mut kept = Vec::with_capacity(self.vocab_size.max(all_chars.len()));
+            let mut rest = Vec::new();
+            for entry in vocab {
+                if all_chars.contains(&entry.0) {
+                    kept.push(entry);
+                } else {
+                    rest.push(entry);
+                }
+            }
+            let budget = self.vocab_size.saturating_sub(kept.len());
+            kept.extend(rest.into_iter().take(budget));
+            kept.sort_by(|a, b| b.1.total_cmp(&a.1));
+            vocab = kept;
+        }
+        vocab
+    }
+
+    pub fn do_train(&self, word_counts: &HashMap<String, u64>, model: &mut Unigram) -> Result<Vec<AddedToken>> {
+        let progress = self.setup_progress();
+
+        let mut pieces = self.seed_pieces(word_counts);
+        self.update_progress(&progress, pieces.len(), "Seed pieces");
+        self.finalize_progress(&progress, pieces.len());
+
+        let all_chars: HashSet<String> = word_counts
+            .keys()
+            .flat_map(|w| w.chars())
+            .map(|c| c.to_string())
+            .collect();
+
+        self.update_progress(&progress, self.vocab_size, "EM + prune");
+        loop {
+            for _ in 0..self.n_em_iterations {
+                let expected_counts = self.e_step(word_counts, &pieces);
+                pieces = self.m_step(expected_counts, &all_chars);
+            }
+
+            if pieces.len() <= self.vocab_size || pieces.len() <= all_chars.len() {
+                break;
+            }
+
+            self.prune(&mut pieces, word_counts, &all_chars);
+            if let Some(p) = &progress {
+                p.set_length(pieces.len() as u64);
+            }
+        }
+        self.finalize_progress(&progress, pieces.len());
+
+        // One final EM pass on the pruned vocabulary
+        let expected_counts = self.e_step(word_counts, &pieces);
+        pieces = self.m_step(expected_counts, &all_chars);
+
+        let mut vocab = self.finalize_vocab(pieces, &all_chars);
+        let unk_id = self.unk_token.as_ref().map(|unk| {
+            vocab.insert(0, (unk.clone(), f64::MIN));
+            0usize
+        });
+
+        *model = Unigram::from(vocab, unk_id, false)?;
+
+        Ok(self.special_tokens.clone())
+    }
+}
+
+impl Trainer for UnigramTrainer {
+    type Model = Unigram;
+
+    /// Train a Unigram model
+    fn train(&self, model: &mut Unigram) -> Result<Vec<AddedToken>> {
+        self.do_train(&self.words, model)
+    }
+
+    /// Whether we should show progress
+    fn should_show_progress(&self) -> bool {
+        self.show_progress
+    }
+
+    fn feed<I, S, F>(&mut self, iterator: I, process: F) -> Result<()>
+    where
+        I: Iterator<Item = S> + Send,
+        S: AsRef<str> + Send,
+        F: Fn(&str) -> Result<Vec<String>> + Sync,
+    {
+        let words: Result<HashMap<String, u64>> = iterator
+            .maybe_par_bridge()
+            .map(|sequence| {
+                let words = process(sequence.as_ref())?;
+                let mut map = HashMap::new();
+                for word in words {
+                    map.entry(word).and_modify(|c| *c += 1).or_insert(1);
+                }
+                Ok(map)
+            })
+            .reduce(
+                || Ok(HashMap::new()),
+                |acc, ws| {
+                    let mut acc = acc?;
+                    for (k, v) in ws? {
+                        acc.entry(k).and_modify(|c| *c += v).or_insert(v);
+                    }
+                    Ok(acc)
+                },
+            );
+
+        self.words = words?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Unigram, UnigramTrainer};
+    use std::collections::{HashMap, HashSet};
+
+    fn word_counts() -> HashMap<String, u64> {
+        [
+            ("roses".into(), 1),
+            ("are".into(), 2),
+            ("red".into(), 1),
+            ("violets".into(), 1),
+            ("blue".into(), 1),
+            ("is".into(), 2),
+            ("big".into(), 1),
+        ]
+        .iter()
+        .cloned()
+        .collect()
+    }
+
+    fn all_chars(word_counts: &HashMap<String, u64>) -> HashSet<String> {
+        word_counts
+            .keys()
+            .flat_map(|w| w.chars())
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_train_covers_every_char() {
+        let word_counts = word_counts();
+        let trainer = UnigramTrainer::builder()
+            .vocab_size(20)
+            .show_progress(false)
+            .build();
+        let mut model = Unigram::default();
+        trainer.do_train(&word_counts, &mut model).unwrap();
+
+        let vocab = model.get_vocab();
+        for c in all_chars(&word_counts) {
+            assert!(vocab.contains_key(&c), "missing char piece: {}", c);
+        }
+    }
+
+    #[test]
+    fn test_seed_pieces_covers_every_char() {
+        let word_counts = word_counts();
+        let trainer = UnigramTrainer::default();
+        let pieces = trainer.seed_pieces(&word_counts);
+        for c in all_chars(&word_counts) {
+            assert!(pieces.contains_key(&c), "missing seed char: {}", c);
+        }
+    }
+
+    #[test]
+    fn test_m_step_keeps_chars_with_zero_expected_count() {
+        let trainer = UnigramTrainer::default();
+        let all_chars: HashSet<String> = ["a".to_string(), "b".to_string(), "c".to_string()]
+            .into_iter()
+            .collect();
+        // Simulate a beam search that never picked "c" for any occurrence, so it has no
+        // entry at all in the expected counts (not even a zero one).
+        let expected_counts: HashMap<String, f64> =
+            [("a".to_string(), 4.0), ("ab".to_string(), 2.0)]
+                .into_iter()
+                .collect();
+
+        let pieces = trainer.m_step(expected_counts, &all_chars);
+
+        for c in &all_chars {
+            assert!(pieces.contains_key(c), "m_step dropped char: {}", c);
+        }
+    }
+
+    #[test]
+    fn test_prune_never_drops_below_vocab_size() {
+        let trainer = UnigramTrainer::builder()
+            .vocab_size(5)
+            .shrinking_factor(0.1) // keep only 10% of prunable pieces per round
+            .show_progress(false)
+            .build();
+        let word_counts = word_counts();
+        let all_chars = all_chars(&word_counts);
+
+        let mut pieces = trainer.seed_pieces(&word_counts);
+        let starting_len = pieces.len();
+        trainer.prune(&mut pieces, &word_counts, &all_chars);
+
+        assert!(pieces.len() < starting_len, "prune should have dropped something");
+        assert!(
+            pieces.len() >= trainer.vocab_size.max(all_chars.len()),
+            "prune dropped below the vocab_size/char-count floor: {} pieces left",
+            pieces.len()
+        );
+        for c in &all_chars {
+            assert!(pieces.contains_key(c), "prune dropped char: {}", c);
+        }
+    }
+
+    #[test]
+    fn test_finalize_vocab_keeps_all_chars_under_tight_budget() {
+        let word_counts = word_counts();
+        let all_chars = all_chars(&word_counts);
+        // A budget smaller than the number of distinct chars in the corpus.
+        let trainer = UnigramTrainer::builder()
+            .vocab_size(all_chars.len() - 1)
+            .show_progress(false)
+            .build();
+
+        let pieces = trainer.seed_pieces(&word_counts);
+        let vocab = trainer.finalize_vocab(pieces, &all_chars);
+
+        let pieces_in_vocab: HashSet<&String> = vocab.iter().map(|(piece, _)| piece).collect();
+        for c in &all_chars {
+            assert!(pieces_in_vocab.contains(c), "truncation dropped char: {}", c);
+        }
+    }
+}